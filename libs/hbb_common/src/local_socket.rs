@@ -0,0 +1,257 @@
+//! Local IPC transport for the control channel between the service process
+//! and the UI/tray, so the two don't need to negotiate a loopback TCP port.
+//!
+//! Unix uses a `UnixStream` (including Linux abstract-namespace addresses),
+//! Windows a named pipe; both are wrapped in the same `BytesCodec` `Framed`
+//! pipeline as [`crate::tcp::FramedStream`].
+
+use crate::{bytes_codec::BytesCodec, tcp::FramedStream, ResultType};
+use std::{io, path::PathBuf};
+use tokio_util::codec::Framed;
+
+#[cfg(windows)]
+use tokio::net::windows::named_pipe::{ClientOptions, NamedPipeClient, NamedPipeServer, ServerOptions};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+
+/// `CreateNamedPipe`'s default security descriptor also grants `Everyone`
+/// read access, so the pipe needs an explicit owner/SYSTEM-only DACL.
+#[cfg(windows)]
+mod owner_only_pipe_security {
+    use std::{ffi::c_void, io, ptr};
+    use windows_sys::Win32::{
+        Security::Authorization::ConvertStringSecurityDescriptorToSecurityDescriptorW,
+        Security::SECURITY_ATTRIBUTES,
+        System::Memory::LocalFree,
+    };
+
+    const SDDL: &str = "D:P(A;;GA;;;OW)(A;;GA;;;SY)";
+
+    pub struct OwnerOnlySecurity(SECURITY_ATTRIBUTES);
+
+    impl OwnerOnlySecurity {
+        pub fn new() -> io::Result<Self> {
+            let sddl: Vec<u16> = SDDL.encode_utf16().chain(std::iter::once(0)).collect();
+            let mut descriptor: *mut c_void = ptr::null_mut();
+            let ok = unsafe {
+                ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                    sddl.as_ptr(),
+                    1, // SDDL_REVISION_1
+                    &mut descriptor,
+                    ptr::null_mut(),
+                )
+            };
+            if ok == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self(SECURITY_ATTRIBUTES {
+                nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+                lpSecurityDescriptor: descriptor,
+                bInheritHandle: 0,
+            }))
+        }
+
+        pub fn as_ptr(&mut self) -> *mut c_void {
+            &mut self.0 as *mut SECURITY_ATTRIBUTES as *mut c_void
+        }
+    }
+
+    impl Drop for OwnerOnlySecurity {
+        fn drop(&mut self) {
+            if !self.0.lpSecurityDescriptor.is_null() {
+                unsafe {
+                    LocalFree(self.0.lpSecurityDescriptor as _);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+use owner_only_pipe_security::OwnerOnlySecurity;
+
+#[cfg(windows)]
+fn create_pipe_server(name: &str, first_instance: bool) -> ResultType<NamedPipeServer> {
+    let mut security = OwnerOnlySecurity::new()?;
+    // SAFETY: `security` is valid for the duration of this call.
+    let server = unsafe {
+        ServerOptions::new()
+            .first_pipe_instance(first_instance)
+            .create_with_security_attributes_raw(name, security.as_ptr())?
+    };
+    Ok(server)
+}
+
+/// Where to reach the local control channel. No `Tcp` variant on purpose;
+/// remote/network targets go through `socket_client::connect_tcp_local`.
+#[derive(Debug, Clone)]
+pub enum Address {
+    Unix(PathBuf),
+    /// Linux abstract-namespace name (no leading NUL stored).
+    Abstract(String),
+}
+
+#[cfg(unix)]
+pub type LocalStream = UnixStream;
+#[cfg(windows)]
+pub type LocalStream = NamedPipeClient;
+
+pub type LocalFramedStream = FramedStream<LocalStream>;
+
+// The server side of a named pipe is a distinct type from the client side
+// (`NamedPipeServer` vs. `NamedPipeClient`); Unix has no such split, an
+// accepted `UnixStream` is the same type a client connects with.
+#[cfg(unix)]
+pub type AcceptedStream = UnixStream;
+#[cfg(windows)]
+pub type AcceptedStream = NamedPipeServer;
+
+pub type AcceptedFramedStream = FramedStream<AcceptedStream>;
+
+#[cfg(unix)]
+fn unix_addr(path: &std::path::Path) -> ResultType<std::os::unix::net::SocketAddr> {
+    Ok(std::os::unix::net::SocketAddr::from_pathname(path)?)
+}
+
+/// Narrows the umask around `bind()` so a path-backed socket is never
+/// briefly world-connectable at the umask's default mode. No-op for
+/// abstract-namespace addresses, which have no path.
+#[cfg(unix)]
+fn bind_owner_only(
+    std_addr: &std::os::unix::net::SocketAddr,
+) -> io::Result<std::os::unix::net::UnixListener> {
+    let previous = unsafe { libc::umask(0o177) };
+    let result = std::os::unix::net::UnixListener::bind_addr(std_addr);
+    unsafe { libc::umask(previous) };
+    result
+}
+
+/// Abstract-namespace sockets have no filesystem permissions at all, so
+/// check `SO_PEERCRED`/`LOCAL_PEERCRED` on every accepted connection too.
+#[cfg(unix)]
+fn verify_peer_is_self(stream: &UnixStream) -> ResultType<()> {
+    let cred = stream.peer_cred()?;
+    // SAFETY: `getuid` has no preconditions and cannot fail.
+    let our_uid = unsafe { libc::getuid() };
+    if cred.uid() != our_uid {
+        anyhow::bail!(
+            "rejecting local IPC peer: uid {} does not match our uid {our_uid}",
+            cred.uid()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn abstract_addr(name: &str) -> ResultType<std::os::unix::net::SocketAddr> {
+    use std::os::linux::net::SocketAddrExt;
+    Ok(std::os::unix::net::SocketAddr::from_abstract_name(name)?)
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn abstract_addr(_name: &str) -> ResultType<std::os::unix::net::SocketAddr> {
+    anyhow::bail!("abstract-namespace unix sockets are only supported on Linux")
+}
+
+/// Connect to the local control channel described by `addr`.
+pub async fn connect_local(addr: &Address) -> ResultType<LocalFramedStream> {
+    #[cfg(unix)]
+    {
+        let std_addr = match addr {
+            Address::Unix(path) => unix_addr(path)?,
+            Address::Abstract(name) => abstract_addr(name)?,
+        };
+        let std_stream = std::os::unix::net::UnixStream::connect_addr(&std_addr)?;
+        std_stream.set_nonblocking(true)?;
+        let stream = UnixStream::from_std(std_stream)?;
+        Ok(FramedStream::from_framed(
+            Framed::new(stream, BytesCodec::new()),
+            None,
+            None,
+        ))
+    }
+    #[cfg(windows)]
+    {
+        let name = match addr {
+            Address::Unix(path) => path.to_string_lossy().into_owned(),
+            Address::Abstract(name) => name.clone(),
+        };
+        let stream = ClientOptions::new().open(&name)?;
+        Ok(FramedStream::from_framed(
+            Framed::new(stream, BytesCodec::new()),
+            None,
+            None,
+        ))
+    }
+}
+
+#[cfg(unix)]
+pub struct LocalListener(UnixListener);
+
+#[cfg(windows)]
+pub struct LocalListener {
+    name: String,
+    first: Option<NamedPipeServer>,
+}
+
+/// Start listening on the local control channel described by `addr`.
+pub async fn listen_local(addr: &Address) -> ResultType<LocalListener> {
+    #[cfg(unix)]
+    {
+        if let Address::Unix(path) = addr {
+            let _ = std::fs::remove_file(path);
+        }
+        let std_addr = match addr {
+            Address::Unix(path) => unix_addr(path)?,
+            Address::Abstract(name) => abstract_addr(name)?,
+        };
+        let std_listener = bind_owner_only(&std_addr)?;
+        std_listener.set_nonblocking(true)?;
+        Ok(LocalListener(UnixListener::from_std(std_listener)?))
+    }
+    #[cfg(windows)]
+    {
+        let name = match addr {
+            Address::Unix(path) => path.to_string_lossy().into_owned(),
+            Address::Abstract(name) => name.clone(),
+        };
+        let first = create_pipe_server(&name, true)?;
+        Ok(LocalListener {
+            name,
+            first: Some(first),
+        })
+    }
+}
+
+impl LocalListener {
+    pub async fn accept(&mut self) -> ResultType<AcceptedFramedStream> {
+        #[cfg(unix)]
+        {
+            loop {
+                let (stream, _) = self.0.accept().await?;
+                if verify_peer_is_self(&stream).is_err() {
+                    continue;
+                }
+                return Ok(FramedStream::from_framed(
+                    Framed::new(stream, BytesCodec::new()),
+                    None,
+                    None,
+                ));
+            }
+        }
+        #[cfg(windows)]
+        {
+            let server = match self.first.take() {
+                Some(server) => server,
+                None => create_pipe_server(&self.name, false)?,
+            };
+            server.connect().await?;
+            self.first = Some(create_pipe_server(&self.name, false)?);
+            Ok(FramedStream::from_framed(
+                Framed::new(server, BytesCodec::new()),
+                None,
+                None,
+            ))
+        }
+    }
+}
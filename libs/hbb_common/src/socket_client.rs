@@ -1,5 +1,6 @@
 use crate::{
     config::{Config, NetworkType},
+    local_socket::{self, Address as LocalAddress, LocalFramedStream, LocalListener},
     tcp::FramedStream,
     udp::FramedSocket,
     ResultType,
@@ -128,6 +129,93 @@ pub async fn connect_tcp_local<
     FramedStream::new(target, local, ms_timeout).await
 }
 
+/// Name of the local control channel between the service process and the
+/// UI/tray. Kept distinct from any TCP port so the two can't be reached by
+/// other local users or port-squatted.
+const LOCAL_IPC_NAME: &str = "mini_rustdesk_agent_ipc";
+
+/// Re-lock the mode of a pre-existing runtime dir without following a
+/// symlink another local user may have planted at `dir` in place of a real
+/// directory we own. Opens with `O_NOFOLLOW` and `fchmod`s the fd rather
+/// than `chmod`ing the path, so there's no check-then-act window between
+/// verifying ownership and changing the mode.
+#[cfg(all(unix, not(target_os = "linux")))]
+fn relock_runtime_dir(dir: &std::path::Path, uid: libc::uid_t) -> ResultType<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(dir.as_os_str().as_bytes())?;
+    // SAFETY: `path` is NUL-terminated; the fd is closed below on every path.
+    let fd = unsafe { libc::open(path.as_ptr(), libc::O_NOFOLLOW | libc::O_DIRECTORY) };
+    anyhow::ensure!(fd >= 0, std::io::Error::last_os_error());
+    let result = (|| -> ResultType<()> {
+        // SAFETY: `fd` is open and valid for the duration of this closure.
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        anyhow::ensure!(unsafe { libc::fstat(fd, &mut stat) } == 0, std::io::Error::last_os_error());
+        anyhow::ensure!(
+            stat.st_uid == uid,
+            "refusing to reuse {}: owned by uid {}, not us",
+            dir.display(),
+            stat.st_uid
+        );
+        anyhow::ensure!(unsafe { libc::fchmod(fd, 0o700) } == 0, std::io::Error::last_os_error());
+        Ok(())
+    })();
+    unsafe { libc::close(fd) };
+    result
+}
+
+/// Directory the IPC socket file lives in on Unix platforms without
+/// abstract-namespace sockets (everything but Linux). `std::env::temp_dir()`
+/// is shared and world-writable, so a fixed name under it lets another local
+/// user pre-create or race the path; a per-uid `0700` directory, created
+/// up front rather than `chmod`'d after the fact, closes both holes.
+#[cfg(all(unix, not(target_os = "linux")))]
+fn local_ipc_runtime_dir() -> ResultType<std::path::PathBuf> {
+    use std::os::unix::fs::DirBuilderExt;
+    // SAFETY: `getuid` has no preconditions and cannot fail.
+    let uid = unsafe { libc::getuid() };
+    let dir = std::env::temp_dir().join(format!("mini-rustdesk-agent-{uid}"));
+    match std::fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => relock_runtime_dir(&dir, uid)?,
+        Err(e) => return Err(e.into()),
+    }
+    Ok(dir)
+}
+
+/// Build the platform-appropriate address for the service/UI control
+/// channel: a Linux abstract-namespace socket where available, a Unix
+/// domain socket under the per-user runtime directory elsewhere, or a named
+/// pipe on Windows.
+fn local_ipc_address() -> ResultType<LocalAddress> {
+    #[cfg(target_os = "linux")]
+    {
+        Ok(LocalAddress::Abstract(LOCAL_IPC_NAME.to_owned()))
+    }
+    #[cfg(all(unix, not(target_os = "linux")))]
+    {
+        Ok(LocalAddress::Unix(
+            local_ipc_runtime_dir()?.join(format!("{LOCAL_IPC_NAME}.sock")),
+        ))
+    }
+    #[cfg(windows)]
+    {
+        Ok(LocalAddress::Unix(std::path::PathBuf::from(format!(
+            r"\\.\pipe\{LOCAL_IPC_NAME}"
+        ))))
+    }
+}
+
+/// Connect to the service/UI local control channel.
+pub async fn connect_local_ipc() -> ResultType<LocalFramedStream> {
+    local_socket::connect_local(&local_ipc_address()?).await
+}
+
+/// Start listening on the service/UI local control channel.
+pub async fn listen_local_ipc() -> ResultType<LocalListener> {
+    local_socket::listen_local(&local_ipc_address()?).await
+}
+
 #[inline]
 pub fn is_ipv4(target: &TargetAddr<'_>) -> bool {
     match target {
@@ -166,6 +254,15 @@ async fn test_target(target: &str) -> ResultType<SocketAddr> {
         .context(format!("Failed to look up host for {target}"))
 }
 
+/// Which wildcard address to bind for an incoming-connection socket: when
+/// dual-stack is enabled the `::` v6 wildcard (with `IPV6_V6ONLY` cleared in
+/// `udp::bind_udp`) serves both families from one socket, so it's requested
+/// regardless of which family the peer resolved to.
+#[inline]
+fn any_listen_addr(ipv4: bool) -> String {
+    Config::get_any_listen_addr(ipv4 && !Config::is_dual_stack_enabled())
+}
+
 #[inline]
 pub async fn new_udp_for(
     target: &str,
@@ -178,7 +275,7 @@ pub async fn new_udp_for(
         (true, target.into_target_addr()?)
     };
     Ok((
-        new_udp(Config::get_any_listen_addr(ipv4), ms_timeout).await?,
+        new_udp(any_listen_addr(ipv4), ms_timeout).await?,
         target.to_owned(),
     ))
 }
@@ -209,7 +306,7 @@ pub async fn rebind_udp_for(
     let addr = test_target(target).await?;
     let v4 = addr.is_ipv4();
     Ok(Some((
-        FramedSocket::new(Config::get_any_listen_addr(v4)).await?,
+        FramedSocket::new(any_listen_addr(v4)).await?,
         addr.into_target_addr()?.to_owned(),
     )))
 }
@@ -0,0 +1,186 @@
+use socket2::Socket;
+use std::io;
+
+#[cfg(any(
+    target_os = "ios",
+    target_os = "macos",
+    target_os = "tvos",
+    target_os = "watchos"
+))]
+use socket2::Domain;
+
+/// Low-level socket tuning applied via `socket2` before the stream/listener
+/// socket is created. Every field defaults to "leave the OS default alone",
+/// so opting into one knob doesn't change behavior for the others.
+#[derive(Debug, Clone, Default)]
+pub struct SocketOpts {
+    /// `TCP_NODELAY`. Matters for the interactive input/control channel,
+    /// where Nagle's algorithm adds latency; irrelevant for UDP sockets.
+    pub nodelay: Option<bool>,
+    /// `SO_SNDBUF`, in bytes. Raising this helps bulk video/file-transfer
+    /// throughput over high-bandwidth-delay-product links.
+    pub send_buf_size: Option<usize>,
+    /// `SO_RCVBUF`, in bytes.
+    pub recv_buf_size: Option<usize>,
+    /// `SO_REUSEADDR`, for listeners that need to rebind quickly.
+    pub reuse_addr: bool,
+    /// `SO_REUSEPORT` (Unix only).
+    pub reuse_port: bool,
+    /// `SO_BINDTODEVICE` (Linux) / `IP_BOUND_IF` (macOS/iOS): pin traffic to
+    /// a specific interface, e.g. a VPN or a particular NIC on multi-homed
+    /// hosts. Interface name, e.g. `"eth0"` or `"en0"`.
+    pub bind_to_device: Option<String>,
+}
+
+impl SocketOpts {
+    pub fn apply(&self, socket: &Socket) -> io::Result<()> {
+        if let Some(nodelay) = self.nodelay {
+            if socket.r#type()? == socket2::Type::STREAM {
+                socket.set_nodelay(nodelay)?;
+            }
+        }
+        if let Some(n) = self.send_buf_size {
+            socket.set_send_buffer_size(n)?;
+        }
+        if let Some(n) = self.recv_buf_size {
+            socket.set_recv_buffer_size(n)?;
+        }
+        if self.reuse_addr {
+            socket.set_reuse_address(true)?;
+        }
+        #[cfg(unix)]
+        if self.reuse_port {
+            socket.set_reuse_port(true)?;
+        }
+        self.apply_bind_to_device(socket)?;
+        Ok(())
+    }
+
+    #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+    fn apply_bind_to_device(&self, socket: &Socket) -> io::Result<()> {
+        if let Some(dev) = &self.bind_to_device {
+            socket.bind_device(Some(dev.as_bytes()))?;
+        }
+        Ok(())
+    }
+
+    #[cfg(any(
+        target_os = "ios",
+        target_os = "macos",
+        target_os = "tvos",
+        target_os = "watchos"
+    ))]
+    fn apply_bind_to_device(&self, socket: &Socket) -> io::Result<()> {
+        let Some(dev) = &self.bind_to_device else {
+            return Ok(());
+        };
+        let name = std::ffi::CString::new(dev.as_str())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if index == 0 {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "unknown interface"));
+        }
+        let index = std::num::NonZeroU32::new(index);
+        // `IP_BOUND_IF`/`IPV6_BOUND_IF` are per-family, so pick the call that
+        // matches this socket's domain and propagate *its* error (permission
+        // denied, stale index, ...) instead of silently leaving the socket
+        // unpinned; the other family's call would always fail here anyway.
+        match socket.domain()? {
+            Domain::IPV6 => socket.bind_device_by_index_v6(index),
+            _ => socket.bind_device_by_index_v4(index),
+        }
+    }
+
+    #[cfg(not(any(
+        target_os = "android",
+        target_os = "fuchsia",
+        target_os = "linux",
+        target_os = "ios",
+        target_os = "macos",
+        target_os = "tvos",
+        target_os = "watchos"
+    )))]
+    fn apply_bind_to_device(&self, _socket: &Socket) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use socket2::{Domain, Type};
+
+    fn tcp_socket() -> Socket {
+        Socket::new(Domain::IPV4, Type::STREAM, None).unwrap()
+    }
+
+    #[test]
+    fn default_leaves_every_option_alone() {
+        let socket = tcp_socket();
+        // `Default` means "don't touch anything": applying it must succeed
+        // even though nothing in `SocketOpts` is set.
+        SocketOpts::default().apply(&socket).unwrap();
+    }
+
+    #[test]
+    fn nodelay_is_applied() {
+        let socket = tcp_socket();
+        let opts = SocketOpts {
+            nodelay: Some(true),
+            ..Default::default()
+        };
+        opts.apply(&socket).unwrap();
+        assert!(socket.nodelay().unwrap());
+    }
+
+    #[test]
+    fn reuse_addr_is_applied() {
+        let socket = tcp_socket();
+        let opts = SocketOpts {
+            reuse_addr: true,
+            ..Default::default()
+        };
+        opts.apply(&socket).unwrap();
+        assert!(socket.reuse_address().unwrap());
+    }
+
+    #[test]
+    fn buffer_sizes_are_applied() {
+        let socket = tcp_socket();
+        let opts = SocketOpts {
+            send_buf_size: Some(64 * 1024),
+            recv_buf_size: Some(64 * 1024),
+            ..Default::default()
+        };
+        opts.apply(&socket).unwrap();
+        // The kernel is free to round these up, so check they are at least
+        // the requested size rather than an exact value.
+        assert!(socket.send_buffer_size().unwrap() >= 64 * 1024);
+        assert!(socket.recv_buffer_size().unwrap() >= 64 * 1024);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reuse_port_is_applied() {
+        let socket = tcp_socket();
+        let opts = SocketOpts {
+            reuse_port: true,
+            ..Default::default()
+        };
+        opts.apply(&socket).unwrap();
+        assert!(socket.reuse_port().unwrap());
+    }
+
+    #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+    #[test]
+    fn bind_to_device_rejects_unknown_interface() {
+        let socket = tcp_socket();
+        let opts = SocketOpts {
+            bind_to_device: Some("definitely-not-a-real-nic".to_owned()),
+            ..Default::default()
+        };
+        // `SO_BINDTODEVICE` with a bogus name should surface an error
+        // rather than silently leaving the socket unpinned.
+        assert!(opts.apply(&socket).is_err());
+    }
+}
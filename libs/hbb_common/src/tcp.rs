@@ -0,0 +1,386 @@
+use crate::{bytes_codec::BytesCodec, config::Config, socket_opts::SocketOpts, ResultType};
+use bytes::{Bytes, BytesMut};
+use futures::{SinkExt, StreamExt};
+use socket2::{Domain, Protocol, Socket, TcpKeepalive, Type};
+use std::{
+    io,
+    net::SocketAddr,
+    ops::{Deref, DerefMut},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpStream, ToSocketAddrs},
+};
+use tokio_socks::{tcp::Socks5Stream, IntoTargetAddr};
+use tokio_util::codec::Framed;
+
+/// TCP keepalive knobs applied to relay and rendezvous connections. `interval`
+/// and `retries` are best-effort; platforms without per-probe tuning still
+/// get `SO_KEEPALIVE` with `idle` as the OS-level idle time.
+#[derive(Debug, Clone, Copy)]
+pub struct TcpKeepaliveConfig {
+    pub idle: Duration,
+    pub interval: Duration,
+    pub retries: u32,
+}
+
+impl Default for TcpKeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            idle: Duration::from_secs(30),
+            interval: Duration::from_secs(10),
+            retries: 3,
+        }
+    }
+}
+
+fn apply_keepalive(socket: &Socket, keepalive: &TcpKeepaliveConfig) -> io::Result<()> {
+    let mut ka = TcpKeepalive::new().with_time(keepalive.idle);
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "windows"
+    ))]
+    {
+        ka = ka.with_interval(keepalive.interval);
+    }
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+    {
+        ka = ka.with_retries(keepalive.retries);
+    }
+    socket.set_tcp_keepalive(&ka)
+}
+
+/// Open a `TcpStream` via `socket2` so keepalive/`SocketOpts` can be applied
+/// before the connection is established.
+async fn connect_socket(
+    addr: SocketAddr,
+    local: Option<SocketAddr>,
+    keepalive: Option<TcpKeepaliveConfig>,
+    opts: &SocketOpts,
+) -> ResultType<TcpStream> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_nonblocking(true)?;
+    opts.apply(&socket)?;
+    if let Some(local) = local {
+        socket.bind(&local.into())?;
+    }
+    if let Some(keepalive) = keepalive {
+        apply_keepalive(&socket, &keepalive)?;
+    }
+    match socket.connect(&addr.into()) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+        #[cfg(unix)]
+        Err(e) if e.raw_os_error() == Some(libc::EINPROGRESS) => {}
+        Err(e) => return Err(e.into()),
+    }
+    let stream = TcpStream::from_std(socket.into())?;
+    stream.writable().await?;
+    if let Some(err) = stream.take_error()? {
+        return Err(err.into());
+    }
+    Ok(stream)
+}
+
+/// RFC 8305 "Connection Attempt Delay".
+const HAPPY_EYEBALLS_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Sort resolved addresses by interleaving families, starting with IPv6
+/// (v6, v4, v6, v4, ...), per RFC 8305 §4.1.
+fn interleave_addrs(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let mut v6 = std::collections::VecDeque::new();
+    let mut v4 = std::collections::VecDeque::new();
+    for addr in addrs {
+        if addr.is_ipv6() {
+            v6.push_back(addr);
+        } else {
+            v4.push_back(addr);
+        }
+    }
+    let mut out = Vec::with_capacity(v6.len() + v4.len());
+    while !v6.is_empty() || !v4.is_empty() {
+        if let Some(addr) = v6.pop_front() {
+            out.push(addr);
+        }
+        if let Some(addr) = v4.pop_front() {
+            out.push(addr);
+        }
+    }
+    out
+}
+
+/// Race connects across `addrs` (Happy Eyeballs, RFC 8305) and return the
+/// first handshake to complete. Caller wraps this in an overall deadline via
+/// `crate::timeout`.
+async fn connect_happy_eyeballs(
+    addrs: Vec<SocketAddr>,
+    local: Option<SocketAddr>,
+    keepalive: TcpKeepaliveConfig,
+    opts: &SocketOpts,
+) -> ResultType<TcpStream> {
+    use futures::stream::FuturesUnordered;
+
+    let addrs = interleave_addrs(addrs);
+    let mut remaining = addrs.into_iter();
+    let mut pending = FuturesUnordered::new();
+    if let Some(addr) = remaining.next() {
+        pending.push(connect_socket(addr, local, Some(keepalive), opts));
+    }
+
+    let mut last_err = None;
+    loop {
+        if pending.is_empty() && remaining.as_slice().is_empty() {
+            return Err(last_err.unwrap_or_else(|| anyhow::anyhow!("no addresses to connect to")));
+        }
+        tokio::select! {
+            Some(result) = pending.next(), if !pending.is_empty() => {
+                match result {
+                    Ok(stream) => return Ok(stream),
+                    Err(e) => {
+                        last_err = Some(e);
+                        if let Some(addr) = remaining.next() {
+                            pending.push(connect_socket(addr, local, Some(keepalive), opts));
+                        }
+                    }
+                }
+            }
+            _ = tokio::time::sleep(HAPPY_EYEBALLS_ATTEMPT_DELAY), if !remaining.as_slice().is_empty() => {
+                if let Some(addr) = remaining.next() {
+                    pending.push(connect_socket(addr, local, Some(keepalive), opts));
+                }
+            }
+        }
+    }
+}
+
+/// A framed message stream over some `AsyncRead + AsyncWrite` transport.
+/// Defaults to `TcpStream`; the local IPC transport in `local_socket`
+/// instantiates this over a `UnixStream`/named pipe instead.
+pub struct FramedStream<S = TcpStream>(Framed<S, BytesCodec>, Option<SocketAddr>, Option<SocketAddr>);
+
+impl<S> Deref for FramedStream<S> {
+    type Target = Framed<S, BytesCodec>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<S> DerefMut for FramedStream<S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> FramedStream<S> {
+    pub(crate) fn from_framed(
+        inner: Framed<S, BytesCodec>,
+        peer_addr: Option<SocketAddr>,
+        local_addr: Option<SocketAddr>,
+    ) -> Self {
+        Self(inner, peer_addr, local_addr)
+    }
+
+    pub fn set_send_timeout(&mut self, _ms: u64) {}
+
+    #[inline]
+    pub fn set_raw(&mut self) {
+        self.0.codec_mut().set_raw();
+    }
+
+    pub async fn send_bytes(&mut self, bytes: Bytes) -> ResultType<()> {
+        self.0.send(bytes).await?;
+        Ok(())
+    }
+
+    pub async fn next_timeout(&mut self, ms: u64) -> Option<Result<BytesMut, io::Error>> {
+        crate::timeout(ms, self.0.next()).await.ok().flatten()
+    }
+}
+
+impl FramedStream<TcpStream> {
+    /// Infallible, unlike the generic transport: a connected `TcpStream`
+    /// always has a peer/local address.
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.1.unwrap_or_else(|| "0.0.0.0:0".parse().unwrap())
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.2.unwrap_or_else(|| "0.0.0.0:0".parse().unwrap())
+    }
+
+    pub async fn new<T: ToSocketAddrs + std::fmt::Display>(
+        remote_addr: T,
+        local_addr: Option<SocketAddr>,
+        ms_timeout: u64,
+    ) -> ResultType<Self> {
+        let addrs: Vec<SocketAddr> =
+            crate::timeout(ms_timeout, tokio::net::lookup_host(&remote_addr))
+                .await??
+                .collect();
+        anyhow::ensure!(!addrs.is_empty(), "Failed to resolve {remote_addr}");
+        let stream = crate::timeout(
+            ms_timeout,
+            connect_happy_eyeballs(
+                addrs,
+                local_addr,
+                Config::get_tcp_keepalive(),
+                &Config::get_socket_opts(),
+            ),
+        )
+        .await??;
+        let addr = stream.peer_addr()?;
+        let local_addr = stream.local_addr().ok();
+        Ok(Self(
+            Framed::new(stream, BytesCodec::new()),
+            Some(addr),
+            local_addr,
+        ))
+    }
+
+    pub async fn connect<'a, 't>(
+        target: impl IntoTargetAddr<'t>,
+        local: Option<SocketAddr>,
+        proxy: &crate::config::Socks5Server,
+        ms_timeout: u64,
+    ) -> ResultType<Self> {
+        let proxy_addrs: Vec<SocketAddr> =
+            crate::timeout(ms_timeout, tokio::net::lookup_host(&proxy.proxy))
+                .await??
+                .collect();
+        anyhow::ensure!(!proxy_addrs.is_empty(), "Failed to resolve {}", proxy.proxy);
+        let socket = crate::timeout(
+            ms_timeout,
+            connect_happy_eyeballs(
+                proxy_addrs,
+                local,
+                Config::get_tcp_keepalive(),
+                &Config::get_socket_opts(),
+            ),
+        )
+        .await??;
+        let stream = if proxy.username.is_empty() {
+            crate::timeout(
+                ms_timeout,
+                Socks5Stream::connect_with_socket(socket, target),
+            )
+            .await??
+        } else {
+            crate::timeout(
+                ms_timeout,
+                Socks5Stream::connect_with_password_and_socket(
+                    socket,
+                    target,
+                    &proxy.username,
+                    &proxy.password,
+                ),
+            )
+            .await??
+        };
+        let stream = stream.into_inner();
+        let addr = stream.peer_addr()?;
+        let local_addr = stream.local_addr().ok();
+        Ok(Self(
+            Framed::new(stream, BytesCodec::new()),
+            Some(addr),
+            local_addr,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bind then immediately drop a listener: the port is free but nothing
+    /// is behind it, so a connect attempt fails fast (`ECONNREFUSED`)
+    /// instead of hanging until a timeout.
+    fn unreachable_addr() -> SocketAddr {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    #[test]
+    fn interleave_addrs_alternates_families_starting_with_v6() {
+        let v4a: SocketAddr = "1.2.3.4:1".parse().unwrap();
+        let v4b: SocketAddr = "1.2.3.5:1".parse().unwrap();
+        let v6a: SocketAddr = "[::1]:1".parse().unwrap();
+        let v6b: SocketAddr = "[::2]:1".parse().unwrap();
+
+        assert_eq!(
+            interleave_addrs(vec![v4a, v4b, v6a, v6b]),
+            vec![v6a, v4a, v6b, v4b]
+        );
+    }
+
+    #[test]
+    fn interleave_addrs_handles_uneven_and_single_family_lists() {
+        let v4: SocketAddr = "1.2.3.4:1".parse().unwrap();
+        let v6a: SocketAddr = "[::1]:1".parse().unwrap();
+        let v6b: SocketAddr = "[::2]:1".parse().unwrap();
+
+        // More v6 than v4: the leftover v6 address trails once v4 runs out.
+        assert_eq!(interleave_addrs(vec![v4, v6a, v6b]), vec![v6a, v4, v6b]);
+        // Single-family list: passed through unchanged.
+        assert_eq!(interleave_addrs(vec![v4]), vec![v4]);
+        assert_eq!(interleave_addrs(vec![]), Vec::<SocketAddr>::new());
+    }
+
+    #[tokio::test]
+    async fn happy_eyeballs_connects_to_a_reachable_address() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = listener.accept().await;
+        });
+
+        let stream = connect_happy_eyeballs(
+            vec![addr],
+            None,
+            TcpKeepaliveConfig::default(),
+            &SocketOpts::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+    }
+
+    #[tokio::test]
+    async fn happy_eyeballs_falls_through_a_failed_attempt_to_the_next_address() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let good_addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = listener.accept().await;
+        });
+
+        // First address refuses the connection immediately; the racer
+        // should move on to the second without waiting out the attempt
+        // delay or the overall timeout.
+        let stream = connect_happy_eyeballs(
+            vec![unreachable_addr(), good_addr],
+            None,
+            TcpKeepaliveConfig::default(),
+            &SocketOpts::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), good_addr);
+    }
+
+    #[tokio::test]
+    async fn happy_eyeballs_fails_when_every_address_is_unreachable() {
+        let result = connect_happy_eyeballs(
+            vec![unreachable_addr(), unreachable_addr()],
+            None,
+            TcpKeepaliveConfig::default(),
+            &SocketOpts::default(),
+        )
+        .await;
+        assert!(result.is_err());
+    }
+}
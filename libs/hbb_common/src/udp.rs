@@ -0,0 +1,108 @@
+use crate::{bytes_codec::BytesCodec, config::Config, socket_opts::SocketOpts, ResultType};
+use bytes::{Bytes, BytesMut};
+use futures::{SinkExt, StreamExt};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::SocketAddr;
+use tokio::net::{ToSocketAddrs, UdpSocket};
+use tokio_socks::{udp::Socks5Datagram, IntoTargetAddr, TargetAddr, ToProxyAddrs};
+use tokio_util::udp::UdpFramed;
+
+async fn bind_udp<T: ToSocketAddrs>(local: T, opts: &SocketOpts) -> ResultType<UdpSocket> {
+    let addr = tokio::net::lookup_host(local)
+        .await?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Failed to resolve bind address"))?;
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_nonblocking(true)?;
+    // Binding the `::` wildcard with `IPV6_V6ONLY` cleared lets one socket
+    // serve mapped-IPv4 and native IPv6 peers, so callers don't need to open
+    // (and the agent doesn't need to juggle) a second v4 socket.
+    if is_unspecified_v6(&addr) && Config::is_dual_stack_enabled() {
+        socket.set_only_v6(false)?;
+    }
+    opts.apply(&socket)?;
+    socket.bind(&addr.into())?;
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+fn is_unspecified_v6(addr: &SocketAddr) -> bool {
+    matches!(addr, SocketAddr::V6(a) if a.ip().is_unspecified())
+}
+
+/// A UDP socket framed with [`BytesCodec`], or a SOCKS5-relayed datagram
+/// socket when the user has configured a proxy. Both variants speak the
+/// same `send_to`/`next_timeout` surface so callers don't need to care which
+/// one they got.
+pub enum FramedSocket {
+    Direct(UdpFramed<BytesCodec>),
+    ProxySocks(Socks5Datagram<tokio::net::TcpStream>),
+}
+
+impl FramedSocket {
+    pub async fn new<T: ToSocketAddrs>(local: T) -> ResultType<Self> {
+        let socket = bind_udp(local, &Config::get_socket_opts()).await?;
+        Ok(Self::Direct(UdpFramed::new(socket, BytesCodec::new())))
+    }
+
+    pub async fn new_proxy<'a, 't, T: ToSocketAddrs>(
+        proxy: &'a str,
+        local: T,
+        username: &'a str,
+        password: &'a str,
+        ms_timeout: u64,
+    ) -> ResultType<Self> {
+        let socket = bind_udp(local, &Config::get_socket_opts()).await?;
+        let datagram = if username.is_empty() {
+            crate::timeout(ms_timeout, Socks5Datagram::bind(proxy, socket)).await??
+        } else {
+            crate::timeout(
+                ms_timeout,
+                Socks5Datagram::bind_with_password(proxy, socket, username, password),
+            )
+            .await??
+        };
+        Ok(Self::ProxySocks(datagram))
+    }
+
+    pub async fn send_to<'a, 't>(
+        &mut self,
+        msg: &[u8],
+        addr: impl IntoTargetAddr<'t>,
+    ) -> ResultType<()> {
+        match self {
+            Self::Direct(f) => {
+                let target = addr.into_target_addr()?;
+                let addr = match target {
+                    TargetAddr::Ip(addr) => addr,
+                    TargetAddr::Domain(host, port) => tokio::net::lookup_host((host.as_ref(), port))
+                        .await?
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("Failed to resolve {host}"))?,
+                };
+                f.send((Bytes::copy_from_slice(msg), addr)).await?;
+            }
+            Self::ProxySocks(s) => {
+                s.send_to(msg, addr).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn next_timeout(&mut self, ms: u64) -> Option<ResultType<(BytesMut, SocketAddr)>> {
+        match self {
+            Self::Direct(f) => crate::timeout(ms, f.next())
+                .await
+                .ok()
+                .flatten()
+                .map(|r| r.map_err(anyhow::Error::from)),
+            Self::ProxySocks(s) => {
+                let mut buf = vec![0u8; 65536];
+                match crate::timeout(ms, s.recv_from(&mut buf)).await {
+                    Ok(Ok((n, addr))) => Some(Ok((BytesMut::from(&buf[..n]), addr))),
+                    Ok(Err(e)) => Some(Err(e.into())),
+                    Err(_) => None,
+                }
+            }
+        }
+    }
+}